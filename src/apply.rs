@@ -1,4 +1,5 @@
-use std::os;
+use divide;
+use registry::{self, Registry};
 
 /// Parallelizes `operation` over given `data`.
 ///
@@ -28,9 +29,21 @@ pub fn apply<T, F>(data: &mut [T], operation: F) where
     T: Send,
     F: Fn(&mut T) + Sync,
 {
-    let granularity = data.len() / os::num_cpus() + 1;
+    let registry = registry::default_registry();
+    apply_in(&*registry, data, operation)
+}
+
+/// Same as [`apply`](fn.apply.html), but scheduled onto a specific `Registry` instead of the
+/// default pool. Used by `ThreadPool::apply`. If the calling thread is a worker of any pool,
+/// including a different one, the chunk tasks are pushed onto that worker's own deque rather
+/// than handed to `registry`.
+pub fn apply_in<T, F>(registry: &Registry, data: &mut [T], operation: F) where
+    T: Send,
+    F: Fn(&mut T) + Sync,
+{
+    let granularity = data.len() / registry.num_threads() + 1;
 
-    ::divide(data, granularity, |data, _|{
+    divide::divide_in(registry, data, granularity, |data, _| {
         for e in data.iter_mut() {
             operation(e);
         }
@@ -0,0 +1,280 @@
+//! A Chase-Lev work-stealing deque
+//!
+//! The owning worker pushes and pops from the *bottom* of the deque; other workers
+//! (thieves) steal from the *top*. This is the data structure that lets the
+//! [`Registry`](../registry/struct.Registry.html) balance chunk-tasks across worker threads
+//! without a central lock on the common case (push/pop never contend with steals on the fast
+//! path).
+//!
+//! Reference: Chase, D. and Lev, Y., "Dynamic Circular Work-Stealing Deque" (SPAA 2005).
+
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::atomic::{self, AtomicIsize, AtomicPtr, Ordering};
+
+/// A growable circular buffer of `T`, indexed modulo its (power-of-two) length
+struct Buffer<T> {
+    storage: Vec<UnsafeCell<*mut T>>,
+}
+
+impl<T> Buffer<T> {
+    fn new(size: usize) -> Buffer<T> {
+        Buffer { storage: (0..size).map(|_| UnsafeCell::new(ptr::null_mut())).collect() }
+    }
+
+    fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    unsafe fn get(&self, i: isize) -> *mut T {
+        *self.storage[i as usize & (self.len() - 1)].get()
+    }
+
+    unsafe fn put(&self, i: isize, item: *mut T) {
+        *self.storage[i as usize & (self.len() - 1)].get() = item;
+    }
+
+    /// Copies the live range `[top, bottom)` into a new, twice-as-large buffer
+    unsafe fn grow(&self, top: isize, bottom: isize) -> Buffer<T> {
+        let grown = Buffer::new(self.len() * 2);
+
+        for i in top..bottom {
+            grown.put(i, self.get(i));
+        }
+
+        grown
+    }
+}
+
+/// The owner's half of a work-stealing deque: the only handle allowed to `push`/`pop`
+pub struct Deque<T> {
+    top: AtomicIsize,
+    bottom: AtomicIsize,
+    buffer: AtomicPtr<Buffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Deque<T> {}
+unsafe impl<T: Send> Sync for Deque<T> {}
+
+impl<T> Deque<T> {
+    pub fn new() -> Deque<T> {
+        let buffer = Box::new(Buffer::new(32));
+
+        Deque {
+            top: AtomicIsize::new(0),
+            bottom: AtomicIsize::new(0),
+            buffer: AtomicPtr::new(Box::into_raw(buffer)),
+        }
+    }
+
+    /// Returns a thief handle that can `steal` from this deque
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            top: &self.top as *const _,
+            bottom: &self.bottom as *const _,
+            buffer: &self.buffer as *const _,
+        }
+    }
+
+    /// Pushes `item` onto the bottom of the deque. Only the owner may call this.
+    pub fn push(&self, item: T) {
+        let bottom = self.bottom.load(Ordering::Relaxed);
+        let top = self.top.load(Ordering::Acquire);
+
+        let mut buffer = unsafe { &*self.buffer.load(Ordering::Relaxed) };
+
+        if bottom - top >= buffer.len() as isize {
+            let grown = Box::new(unsafe { buffer.grow(top, bottom) });
+            let grown = Box::into_raw(grown);
+            self.buffer.store(grown, Ordering::Release);
+            buffer = unsafe { &*grown };
+            // NB the old buffer is intentionally leaked: a concurrent `steal` may still
+            // hold a reference to it and we have no epoch/hazard-pointer scheme to know
+            // when it's safe to free.
+        }
+
+        unsafe { buffer.put(bottom, Box::into_raw(Box::new(item))) };
+        self.bottom.store(bottom + 1, Ordering::Release);
+    }
+
+    /// Pops an item from the bottom of the deque. Only the owner may call this.
+    ///
+    /// Returns `None` if the deque is (or becomes, racing a thief) empty.
+    pub fn pop(&self) -> Option<T> {
+        let bottom = self.bottom.load(Ordering::Relaxed) - 1;
+        let buffer = unsafe { &*self.buffer.load(Ordering::Relaxed) };
+        self.bottom.store(bottom, Ordering::Relaxed);
+
+        // without this, a thief could still observe the pre-decrement `bottom` (this store is
+        // `Relaxed`, not `Release`) and race us for what looks to it like the last element, even
+        // though we've already claimed it below -- on non-TSO hardware nothing otherwise orders
+        // our store to `bottom` before our load of `top`
+        atomic::fence(Ordering::SeqCst);
+
+        let top = self.top.load(Ordering::Relaxed);
+
+        if top > bottom {
+            // already empty; undo the speculative decrement
+            self.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+
+        let item = unsafe { buffer.get(bottom) };
+
+        if top == bottom {
+            // last element: race a thief for it via CAS on `top`
+            let won = self.top.compare_and_swap(top, top + 1, Ordering::SeqCst) == top;
+            self.bottom.store(bottom + 1, Ordering::Relaxed);
+
+            if !won {
+                return None;
+            }
+        }
+
+        Some(*unsafe { Box::from_raw(item) })
+    }
+}
+
+impl<T> Drop for Deque<T> {
+    fn drop(&mut self) {
+        // drain any items still owned by this deque so their destructors run
+        while self.pop().is_some() {}
+
+        unsafe { drop(Box::from_raw(self.buffer.load(Ordering::Relaxed))) };
+    }
+}
+
+/// A thief handle that can `steal` from the top of someone else's [`Deque`](struct.Deque.html)
+pub struct Stealer<T> {
+    top: *const AtomicIsize,
+    bottom: *const AtomicIsize,
+    buffer: *const AtomicPtr<Buffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Stealer<T> {}
+unsafe impl<T: Send> Sync for Stealer<T> {}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Stealer<T> {
+        Stealer { top: self.top, bottom: self.bottom, buffer: self.buffer }
+    }
+}
+
+pub enum Steal<T> {
+    /// There was nothing to steal
+    Empty,
+    /// Lost the race for the top item to another thief (or to the owner popping the last
+    /// item); retry later
+    Retry,
+    /// Successfully stole an item
+    Data(T),
+}
+
+impl<T> Stealer<T> {
+    pub fn steal(&self) -> Steal<T> {
+        let top = unsafe { &*self.top };
+        let bottom = unsafe { &*self.bottom };
+        let buffer = unsafe { &*self.buffer };
+
+        let t = top.load(Ordering::Acquire);
+        let b = bottom.load(Ordering::Acquire);
+
+        if t >= b {
+            return Steal::Empty;
+        }
+
+        let buffer = unsafe { &*buffer.load(Ordering::Acquire) };
+        let item = unsafe { buffer.get(t) };
+
+        if top.compare_and_swap(t, t + 1, Ordering::SeqCst) != t {
+            return Steal::Retry;
+        }
+
+        Steal::Data(*unsafe { Box::from_raw(item) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    #[test]
+    fn push_pop() {
+        let deque = super::Deque::new();
+
+        deque.push(1i32);
+        deque.push(2);
+        deque.push(3);
+
+        assert_eq!(deque.pop(), Some(3));
+        assert_eq!(deque.pop(), Some(2));
+        assert_eq!(deque.pop(), Some(1));
+        assert_eq!(deque.pop(), None);
+    }
+
+    #[test]
+    fn steal() {
+        let deque = super::Deque::new();
+
+        for i in 0..100i32 {
+            deque.push(i);
+        }
+
+        let stealer = deque.stealer();
+        let mut stolen = vec![];
+
+        loop {
+            match stealer.steal() {
+                super::Steal::Data(x) => stolen.push(x),
+                super::Steal::Empty => break,
+                super::Steal::Retry => continue,
+            }
+        }
+
+        let mut owned = vec![];
+        while let Some(x) = deque.pop() {
+            owned.push(x);
+        }
+
+        let mut all = stolen;
+        all.extend(owned);
+        all.sort();
+
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn concurrent_steal() {
+        let deque = super::Deque::new();
+
+        for i in 0..10_000i32 {
+            deque.push(i);
+        }
+
+        let stealers: Vec<_> = (0..4).map(|_| deque.stealer()).collect();
+        let guards: Vec<_> = stealers.into_iter().map(|stealer| {
+            thread::spawn(move || {
+                let mut stolen = vec![];
+
+                loop {
+                    match stealer.steal() {
+                        super::Steal::Data(x) => stolen.push(x),
+                        super::Steal::Empty => break,
+                        super::Steal::Retry => continue,
+                    }
+                }
+
+                stolen
+            })
+        }).collect();
+
+        let mut all: Vec<i32> = guards.into_iter().flat_map(|g| g.join().unwrap()).collect();
+
+        while let Some(x) = deque.pop() {
+            all.push(x);
+        }
+
+        all.sort();
+        assert_eq!(all, (0..10_000).collect::<Vec<_>>());
+    }
+}
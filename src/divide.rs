@@ -1,9 +1,14 @@
-use std::thread;
+use std::sync::Mutex;
+
+use job::{self, HeapJob, JobRef};
+use latch::CountLatch;
+use registry::{self, Registry, WorkerThread};
 
 /// Parallelizes an `operation` over a mutable slice
 ///
-/// The `data` will be divided in chunks of `granularity` size.
-///  A new thread will be spawned to "operate" over each chunk.
+/// The `data` will be divided in chunks of `granularity` size, and each chunk is handed to the
+/// [default thread pool](struct.ThreadPool.html) as an independent task, which lets
+/// `granularity` be tuned for load balancing without paying for a thread spawn per chunk.
 ///
 /// `operation` will receive two arguments:
 ///
@@ -12,7 +17,7 @@ use std::thread;
 ///
 /// # Panics
 ///
-/// Panics if any of the underlying threads panics
+/// Panics if any of the underlying tasks panics
 ///
 /// # Example
 ///
@@ -40,18 +45,56 @@ use std::thread;
 pub fn divide<T, F>(data: &mut [T], granularity: usize, operation: F) where
     T: Send,
     F: Fn(&mut [T], usize) + Sync,
+{
+    divide_in(&*registry::default_registry(), data, granularity, operation)
+}
+
+/// Same as [`divide`](fn.divide.html), but scheduled onto a specific `Registry` instead of the
+/// default pool. Used by `ThreadPool::divide`. If the calling thread is a worker of any pool,
+/// including a different one, the chunk tasks are pushed onto that worker's own deque rather
+/// than handed to `registry`.
+pub fn divide_in<T, F>(registry: &Registry, data: &mut [T], granularity: usize, operation: F) where
+    T: Send,
+    F: Fn(&mut [T], usize) + Sync,
 {
     assert!(granularity > 0);
 
     let operation = &operation;
-    let guards: Vec<_> = data.chunks_mut(granularity).zip(0..).map(|(chunk, i)| {
-        thread::scoped(move || {
-            (*operation)(chunk, i * granularity)
+    let panicked = Mutex::new(None);
+
+    let chunks: Vec<_> = data.chunks_mut(granularity).zip(0..).collect();
+    let latch = CountLatch::new(chunks.len());
+
+    // every `HeapJob` below borrows `panicked` and `latch`, so they (and the jobs) must
+    // outlive the `registry.wait_until` call at the bottom of this function
+    let jobs: Vec<_> = chunks.into_iter().map(|(chunk, i)| {
+        let offset = i * granularity;
+        let panicked = &panicked;
+        let latch = &latch;
+
+        HeapJob::new(move || {
+            if let Err(e) = job::catch_panic(|| (*operation)(chunk, offset)) {
+                *panicked.lock().unwrap() = Some(e);
+            }
+
+            latch.set();
         })
     }).collect();
 
-    for guard in guards {
-        guard.join();
+    for job in &jobs {
+        // safe: `job` outlives this scope, which doesn't return before `latch` is set
+        let job_ref = unsafe { JobRef::new(job) };
+
+        match WorkerThread::current() {
+            Some(worker) => unsafe { (*worker).push(job_ref) },
+            None => registry.inject(job_ref),
+        }
+    }
+
+    registry.wait_until(&latch);
+
+    if let Some(panic) = panicked.into_inner().unwrap() {
+        panic!(panic);
     }
 }
 
@@ -0,0 +1,73 @@
+//! Type-erased, scoped work items
+//!
+//! Tasks queued on the [`registry`](../registry/index.html) routinely capture references into
+//! the caller's stack (e.g. a chunk of `&mut [T]`) rather than owned, `'static` data. Rust has
+//! no way to express "this closure will be run to completion before the borrow it captured
+//! ends", so we erase the lifetime here and lean on every caller (`divide`, `reduce`, `join`,
+//! ...) blocking until the jobs it enqueued have actually run before it returns.
+
+use std::any::Any;
+use std::mem;
+use std::thread;
+
+/// A type-erased job: an object pointer plus the function needed to run it exactly once
+pub struct JobRef {
+    pointer: *mut (),
+    execute_fn: unsafe fn(*mut ()),
+}
+
+unsafe impl Send for JobRef {}
+
+impl JobRef {
+    /// Erases `job`'s lifetime
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that `job` is not moved or dropped before `execute` is called
+    pub unsafe fn new<T: Job>(job: &T) -> JobRef {
+        let fn_ptr: unsafe fn(*mut T) = <T as Job>::execute;
+
+        JobRef {
+            pointer: job as *const T as *mut (),
+            execute_fn: mem::transmute(fn_ptr),
+        }
+    }
+
+    /// Runs the job. May only be called once.
+    pub unsafe fn execute(self) {
+        (self.execute_fn)(self.pointer)
+    }
+}
+
+/// Something that can be run exactly once from a type-erased pointer
+pub trait Job {
+    unsafe fn execute(this: *mut Self);
+}
+
+/// A job that runs an `FnOnce()` closure
+///
+/// The closure is wrapped in an `Option` so that `execute`, which only gets `&mut self` through
+/// an erased pointer, can still move it out and call it by value; the caller's guarantee that
+/// `execute` runs at most once is what makes that `unwrap` safe.
+pub struct HeapJob<F> {
+    func: Option<F>,
+}
+
+impl<F: FnOnce()> HeapJob<F> {
+    pub fn new(func: F) -> HeapJob<F> {
+        HeapJob { func: Some(func) }
+    }
+}
+
+impl<F: FnOnce()> Job for HeapJob<F> {
+    unsafe fn execute(this: *mut Self) {
+        let func = (*this).func.take().expect("HeapJob executed more than once");
+        func()
+    }
+}
+
+/// Runs `func` on the current thread, converting a panic into an `Err` instead of unwinding
+/// past the worker's main loop
+pub fn catch_panic<F, R>(func: F) -> Result<R, Box<Any + Send>> where F: FnOnce() -> R {
+    thread::catch_panic(func)
+}
@@ -0,0 +1,100 @@
+use std::sync::Mutex;
+
+use job::{self, HeapJob, JobRef};
+use latch::Latch;
+use registry::{self, Registry, WorkerThread};
+
+/// Runs two closures, possibly in parallel, and returns both results
+///
+/// `b` always runs on the calling thread. When the calling thread is one of the pool's workers,
+/// `a` is pushed onto its deque: if an idle worker steals it before the calling thread finishes
+/// `b`, it runs there in parallel; if nobody steals it in time, the calling thread just runs
+/// `a` itself once `b` is done, rather than blocking on a worker that may never come. When the
+/// calling thread is not a worker, `a` is handed to the pool and the calling thread blocks
+/// after `b` until a worker has run `a`; it does not run `a` itself.
+///
+/// Unlike [`divide`](fn.divide.html) and [`reduce`](fn.reduce.html), `a` and `b` only need to
+/// be `FnOnce` rather than `Fn + Sync`. Like them, they may capture references instead of
+/// owned, `'static` data (this function is scoped: it blocks until both closures have
+/// returned, so those borrows stay valid for the whole call). That, plus the ability to call `join` again from inside `a` or `b`, is what
+/// makes it suitable for recursive divide-and-conquer algorithms (parallel quicksort, tree
+/// walks, mergesort) where the fixed fan-out of [`execute!`](../parallel_macros/macro.execute!.html)
+/// doesn't fit. A worker thread that's waiting on a stolen `a` keeps draining other pending
+/// work instead of parking, so nested `join`s can't deadlock the pool.
+///
+/// # Panics
+///
+/// If either closure panics, that panic is propagated here once both closures have finished.
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB) where
+    A: FnOnce() -> RA + Send,
+    RA: Send,
+    B: FnOnce() -> RB + Send,
+{
+    join_in(&*registry::default_registry(), a, b)
+}
+
+/// Same as [`join`](fn.join.html), but scheduled onto a specific `Registry` instead of the
+/// default pool. Used by `ThreadPool::join`. If the calling thread is a worker of any pool,
+/// including a different one, `a` is pushed onto that worker's own deque rather than handed
+/// to `registry`.
+pub fn join_in<A, B, RA, RB>(registry: &Registry, a: A, b: B) -> (RA, RB) where
+    A: FnOnce() -> RA + Send,
+    RA: Send,
+    B: FnOnce() -> RB + Send,
+{
+    let a_result = Mutex::new(None);
+    let latch = Latch::new();
+
+    let job_a = HeapJob::new(|| {
+        *a_result.lock().unwrap() = Some(job::catch_panic(a));
+        latch.set();
+    });
+
+    // safe: `job_a` isn't dropped before `latch` is set below, and `wait_until` doesn't
+    // return before that happens either
+    let job_a_ref = unsafe { JobRef::new(&job_a) };
+
+    match WorkerThread::current() {
+        Some(worker) => unsafe { (*worker).push(job_a_ref) },
+        None => registry.inject(job_a_ref),
+    }
+
+    let b_result = job::catch_panic(b);
+
+    // On a worker thread, `a` may already be running on a thief; if so this helps the pool
+    // drain other work while we wait. If nobody stole it, our own deque still has it and we
+    // just pop it straight back off and run it ourselves. Any other thread just blocks until
+    // a worker has run `a`.
+    match WorkerThread::current() {
+        Some(worker) => unsafe { (*worker).wait_until(&latch) },
+        None => latch.wait(),
+    }
+
+    let a_result = a_result.into_inner().unwrap().expect("job_a was never run");
+
+    match (a_result, b_result) {
+        (Ok(ra), Ok(rb)) => (ra, rb),
+        (Err(e), _) => panic!(e),
+        (_, Err(e)) => panic!(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use thread_pool::ThreadPool;
+
+    // With a single worker thread, neither `job_a` ever gets stolen: the worker that would
+    // steal it is always busy running another `job_a` of its own. If `join` blocked on its
+    // latch instead of helping drain the pool while it waits, this would hang forever.
+    #[test]
+    fn nested_join_on_single_thread_pool_does_not_deadlock() {
+        let pool = ThreadPool::new().num_threads(1).build();
+
+        let ((a, b), (c, d)) = pool.join(
+            || pool.join(|| 1, || 2),
+            || pool.join(|| 3, || 4),
+        );
+
+        assert_eq!((a, b, c, d), (1, 2, 3, 4));
+    }
+}
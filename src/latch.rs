@@ -0,0 +1,93 @@
+//! Blocking primitives used to wait for queued jobs to complete
+
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Something `Registry::wait_until`/`WorkerThread::wait_until` can poll without blocking, so a
+/// worker helping drain the pool knows when to stop without ever having to sleep on it
+pub trait Probe {
+    /// Returns `true` once this latch has been set. Never blocks.
+    fn probe(&self) -> bool;
+
+    /// Blocks the calling thread until this latch is set
+    fn wait(&self);
+}
+
+/// A single-use countdown latch: `wait` blocks until `count` calls to `set` have happened
+pub struct CountLatch {
+    count: AtomicUsize,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl CountLatch {
+    pub fn new(count: usize) -> CountLatch {
+        CountLatch {
+            count: AtomicUsize::new(count),
+            lock: Mutex::new(()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Counts one completion down; wakes any waiter once the count reaches zero
+    pub fn set(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _guard = self.lock.lock().unwrap();
+            self.condvar.notify_all();
+        }
+    }
+
+    pub fn wait(&self) {
+        let mut guard = self.lock.lock().unwrap();
+
+        while self.count.load(Ordering::SeqCst) > 0 {
+            guard = self.condvar.wait(guard).unwrap();
+        }
+    }
+}
+
+impl Probe for CountLatch {
+    fn probe(&self) -> bool {
+        self.count.load(Ordering::SeqCst) == 0
+    }
+
+    fn wait(&self) {
+        CountLatch::wait(self)
+    }
+}
+
+/// A single-use boolean latch: `wait` blocks until `set` has been called once
+pub struct Latch {
+    done: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Latch {
+    pub fn new() -> Latch {
+        Latch { done: Mutex::new(false), condvar: Condvar::new() }
+    }
+
+    pub fn set(&self) {
+        let mut done = self.done.lock().unwrap();
+        *done = true;
+        self.condvar.notify_all();
+    }
+
+    pub fn wait(&self) {
+        let mut done = self.done.lock().unwrap();
+
+        while !*done {
+            done = self.condvar.wait(done).unwrap();
+        }
+    }
+}
+
+impl Probe for Latch {
+    fn probe(&self) -> bool {
+        *self.done.lock().unwrap()
+    }
+
+    fn wait(&self) {
+        Latch::wait(self)
+    }
+}
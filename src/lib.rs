@@ -18,10 +18,12 @@
 #![allow(unused_features)]
 #![cfg_attr(test, plugin(quickcheck_macros))]
 #![deny(warnings)]
+#![feature(catch_panic)]
 #![feature(core)]
 #![feature(os)]
 #![feature(plugin)]
 #![feature(std_misc)]
+#![feature(thread_local)]
 
 #[cfg(test)]
 extern crate quickcheck;
@@ -30,6 +32,18 @@ extern crate rand;
 
 pub use divide::divide;
 pub use apply::apply;
+pub use join::join;
+pub use map_ordered::{map_ordered, MapOrdered};
+pub use reduce::reduce;
+pub use thread_pool::{ThreadPool, ThreadPoolBuilder};
 
 mod divide;
 mod apply;
+mod deque;
+mod job;
+mod join;
+mod latch;
+mod map_ordered;
+mod reduce;
+mod registry;
+mod thread_pool;
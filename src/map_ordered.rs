@@ -0,0 +1,194 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+use job::{self, HeapJob, JobRef};
+use latch::CountLatch;
+use registry::{self, WorkerThread};
+
+struct State<R> {
+    ready: BTreeMap<usize, R>,
+    // the lowest input index whose task panicked, with its panic payload
+    panicked: Option<(usize, Box<Any + Send>)>,
+    // set (and stays set) the first time `next` surfaces `panicked`; without this, a caller
+    // that catches the unwind out of `next` and keeps iterating would wait forever at the
+    // index whose task panicked, since that payload has already been taken out of `panicked`
+    poisoned: bool,
+}
+
+struct Shared<R> {
+    lock: Mutex<State<R>>,
+    condvar: Condvar,
+    // counts down as each dispatched job finishes, regardless of whether its result has been
+    // consumed yet; lets `Drop` wait out any still-running jobs before freeing them
+    done: CountLatch,
+}
+
+/// An iterator, returned by [`map_ordered`](fn.map_ordered.html), that yields mapped results in
+/// input order even though `f` ran on the pool in whatever order its chunks happened to finish
+pub struct MapOrdered<R> {
+    shared: Arc<Shared<R>>,
+    next: usize,
+    len: usize,
+    // kept alive only so the `JobRef`s dispatched below stay valid for as long as a caller may
+    // still be pulling results out of this iterator
+    _jobs: Vec<Box<Any>>,
+}
+
+impl<R> Iterator for MapOrdered<R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        if self.next >= self.len {
+            return None;
+        }
+
+        let mut state = self.shared.lock.lock().unwrap();
+
+        loop {
+            if state.poisoned {
+                panic!("parallel::map_ordered: a previous task panicked");
+            }
+
+            if state.panicked.as_ref().map_or(false, |&(index, _)| index == self.next) {
+                let (_, panic) = state.panicked.take().unwrap();
+                state.poisoned = true;
+                panic!(panic);
+            }
+
+            if let Some(result) = state.ready.remove(&self.next) {
+                self.next += 1;
+                return Some(result);
+            }
+
+            state = self.shared.condvar.wait(state).unwrap();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<R> Drop for MapOrdered<R> {
+    fn drop(&mut self) {
+        // wait out any jobs still running before `_jobs` gets freed below, even if the caller
+        // stopped pulling results early
+        self.shared.done.wait();
+    }
+}
+
+/// Parallelizes `f` over `input`, but yields the results in the same order `input` produced them
+///
+/// This is useful for streaming pipelines (decode/transform in parallel, then write out
+/// sequentially): work happens on the pool as soon as it's dispatched, but the iterator blocks
+/// on whichever result is next in line rather than forcing the caller to collect into a
+/// pre-sized output slice like [`divide`](fn.divide.html) does.
+///
+/// # Panics
+///
+/// If any of the underlying tasks panicked, the returned iterator still yields the results
+/// before the first such task's input, then panics when it reaches that input, and on every
+/// call after that
+pub fn map_ordered<I, T, R, F>(input: I, f: F) -> MapOrdered<R> where
+    I: IntoIterator<Item = T>,
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Sync + Send + 'static,
+{
+    let registry = registry::default_registry();
+    // unlike `divide`/`reduce`, this function returns before the dispatched work finishes, so
+    // `f` can't just be borrowed for the duration of the call -- it's shared via `Arc` instead
+    let f = Arc::new(f);
+
+    let items: Vec<_> = input.into_iter().collect();
+    let len = items.len();
+
+    let shared = Arc::new(Shared {
+        lock: Mutex::new(State { ready: BTreeMap::new(), panicked: None, poisoned: false }),
+        condvar: Condvar::new(),
+        done: CountLatch::new(len),
+    });
+
+    let jobs: Vec<_> = items.into_iter().enumerate().map(|(index, item)| {
+        let shared = shared.clone();
+        let f = f.clone();
+
+        Box::new(HeapJob::new(move || {
+            match job::catch_panic(|| (*f)(item)) {
+                Ok(result) => { shared.lock.lock().unwrap().ready.insert(index, result); }
+                Err(e) => {
+                    let mut state = shared.lock.lock().unwrap();
+
+                    // keep the earliest panic: it's the one `next` reaches first
+                    if state.panicked.as_ref().map_or(true, |&(first, _)| index < first) {
+                        state.panicked = Some((index, e));
+                    }
+                }
+            }
+
+            shared.condvar.notify_all();
+            shared.done.set();
+        }))
+    }).collect::<Vec<Box<HeapJob<_>>>>();
+
+    for job in &jobs {
+        // safe: the `Box<HeapJob<_>>` is kept alive inside the returned `MapOrdered` for as
+        // long as its `JobRef` might still be pending or running
+        let job_ref = unsafe { JobRef::new(&**job) };
+
+        match WorkerThread::current() {
+            Some(worker) => unsafe { (*worker).push(job_ref) },
+            None => registry.inject(job_ref),
+        }
+    }
+
+    MapOrdered {
+        shared: shared,
+        next: 0,
+        len: len,
+        _jobs: jobs.into_iter().map(|job| job as Box<Any>).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    #[test]
+    fn preserves_input_order() {
+        let input = (0..50u32).collect::<Vec<_>>();
+
+        // earlier items sleep longer than later ones, so the pool finishes them out of
+        // submission order -- the iterator still has to yield them back in input order
+        let results = super::map_ordered(input.clone(), |x| {
+            thread::sleep_ms(50 - x);
+            x * 2
+        }).collect::<Vec<_>>();
+
+        assert_eq!(results, input.iter().map(|&x| x * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn panic_surfaces_on_next_call() {
+        let mut iter = super::map_ordered(0..10, |x| {
+            if x == 5 {
+                panic!("map_ordered test panic");
+            }
+
+            x
+        });
+
+        for i in 0..5 {
+            assert_eq!(iter.next(), Some(i));
+        }
+
+        // the task at index 5 panicked; pulling it out should surface that panic here,
+        // rather than silently skipping it or hanging
+        assert!(thread::catch_panic(|| iter.next()).is_err());
+
+        // and it should keep surfacing on every subsequent call, not just the first one
+        assert!(thread::catch_panic(|| iter.next()).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Mutex;
+
+use job::{self, HeapJob, JobRef};
+use latch::CountLatch;
+use registry::{self, Registry, WorkerThread};
+
+/// Parallelizes a map-then-combine (aka fold) over `data`, returning a single aggregate
+///
+/// The `data` will be divided in chunks of `granularity` size, just like in
+/// [`divide`](fn.divide.html). Each chunk is reduced to a partial accumulator `A` by `map`,
+/// and the partials are then folded together by `combine`, starting from `identity`.
+///
+/// `combine` must be associative, and `identity` must be its neutral element, because partials
+/// are folded together in whatever order the chunks happen to finish in, not left-to-right
+/// input order.
+///
+/// # Panics
+///
+/// Panics if any of the underlying tasks panics
+///
+/// # Example
+///
+/// Parallel sum
+///
+/// ```
+/// extern crate parallel;
+///
+/// # fn main() {
+/// let v = (0..1_000).collect::<Vec<u32>>();
+/// let sum = parallel::reduce(
+///     &v,
+///     100,
+///     0,
+///     |chunk, _| chunk.iter().fold(0, |acc, &x| acc + x),
+///     |a, b| a + b,
+/// );
+/// assert_eq!(sum, (0..1_000).fold(0, |acc, x| acc + x));
+/// # }
+/// ```
+pub fn reduce<T, A, M, C>(
+    data: &[T],
+    granularity: usize,
+    identity: A,
+    map: M,
+    combine: C,
+) -> A where
+    T: Sync,
+    A: Send,
+    M: Fn(&[T], usize) -> A + Sync,
+    C: Fn(A, A) -> A,
+{
+    reduce_in(&*registry::default_registry(), data, granularity, identity, map, combine)
+}
+
+/// Same as [`reduce`](fn.reduce.html), but scheduled onto a specific `Registry` instead of the
+/// default pool. Used by `ThreadPool::reduce`. If the calling thread is a worker of any pool,
+/// including a different one, the chunk tasks are pushed onto that worker's own deque rather
+/// than handed to `registry`.
+pub fn reduce_in<T, A, M, C>(
+    registry: &Registry,
+    data: &[T],
+    granularity: usize,
+    identity: A,
+    map: M,
+    combine: C,
+) -> A where
+    T: Sync,
+    A: Send,
+    M: Fn(&[T], usize) -> A + Sync,
+    C: Fn(A, A) -> A,
+{
+    assert!(granularity > 0);
+
+    let map = &map;
+    let panicked = Mutex::new(None);
+    let partials = Mutex::new(Vec::new());
+
+    let chunks: Vec<_> = data.chunks(granularity).zip(0..).collect();
+    let latch = CountLatch::new(chunks.len());
+
+    let jobs: Vec<_> = chunks.into_iter().map(|(chunk, i)| {
+        let offset = i * granularity;
+        let panicked = &panicked;
+        let partials = &partials;
+        let latch = &latch;
+
+        HeapJob::new(move || {
+            match job::catch_panic(|| map(chunk, offset)) {
+                Ok(partial) => partials.lock().unwrap().push(partial),
+                Err(e) => *panicked.lock().unwrap() = Some(e),
+            }
+
+            latch.set();
+        })
+    }).collect();
+
+    for job in &jobs {
+        // safe: `job` outlives this scope, which doesn't return before `latch` is set
+        let job_ref = unsafe { JobRef::new(job) };
+
+        match WorkerThread::current() {
+            Some(worker) => unsafe { (*worker).push(job_ref) },
+            None => registry.inject(job_ref),
+        }
+    }
+
+    registry.wait_until(&latch);
+
+    if let Some(panic) = panicked.into_inner().unwrap() {
+        panic!(panic);
+    }
+
+    partials.into_inner().unwrap().into_iter().fold(identity, combine)
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn sum(size: usize, granularity: usize) -> TestResult {
+        if granularity == 0 {
+            return TestResult::discard();
+        }
+
+        let v = (0..size as u64).collect::<Vec<_>>();
+
+        let sum = super::reduce(
+            &v,
+            granularity,
+            0,
+            |chunk, _| chunk.iter().fold(0, |acc, &x| acc + x),
+            |a, b| a + b,
+        );
+
+        TestResult::from_bool(sum == (0..size as u64).fold(0, |acc, x| acc + x))
+    }
+}
@@ -0,0 +1,248 @@
+//! The persistent pool of worker threads that backs `divide`, `reduce`, `join` and friends
+//!
+//! Each worker owns one [`Deque`](../deque/struct.Deque.html) and runs a simple loop: pop a job
+//! from its own deque, and if that's empty, try to steal one from another worker, and if that's
+//! empty too, look at the shared injector queue that non-worker threads use to hand in work.
+//! When everything is empty the worker parks on a condition variable until `inject` or a
+//! `WorkerThread::push` wakes it back up.
+
+use std::collections::VecDeque;
+use std::os;
+use std::sync::{Condvar, Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use deque::{Deque, Steal, Stealer};
+use job::JobRef;
+use latch::Probe;
+
+/// Wraps a `*const Registry` so it can be moved into a spawned worker's closure.
+///
+/// Safe because the pointed-to `Registry` is only ever deallocated from `Registry::drop`,
+/// which joins every worker thread (and thus this pointer is no longer in use) before that
+/// happens.
+struct RegistryPtr(*const Registry);
+
+unsafe impl Send for RegistryPtr {}
+
+struct Sleep {
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl Sleep {
+    fn new() -> Sleep {
+        Sleep { lock: Mutex::new(()), condvar: Condvar::new() }
+    }
+
+    fn wake_all(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.condvar.notify_all();
+    }
+
+    /// Parks the calling thread until the next `wake_all`, or until 1 ms elapses
+    fn snooze(&self) {
+        let guard = self.lock.lock().unwrap();
+        let _ = self.condvar.wait_timeout_ms(guard, 1);
+    }
+}
+
+/// A handle, local to one worker thread, used to push work and help drain the pool
+pub struct WorkerThread {
+    index: usize,
+    deque: Box<Deque<JobRef>>,
+    registry: *const Registry,
+}
+
+thread_local! {
+    static WORKER_THREAD: ::std::cell::Cell<*const WorkerThread> =
+        ::std::cell::Cell::new(::std::ptr::null())
+}
+
+impl WorkerThread {
+    /// Returns the calling thread's `WorkerThread`, if it is one of the pool's own workers
+    pub fn current() -> Option<*const WorkerThread> {
+        let ptr = WORKER_THREAD.with(|cell| cell.get());
+
+        if ptr.is_null() { None } else { Some(ptr) }
+    }
+
+    /// Pushes `job` onto this worker's own deque, where idle workers may steal it
+    pub unsafe fn push(&self, job: JobRef) {
+        self.deque.push(job);
+        (*self.registry).sleep.wake_all();
+    }
+
+    /// Pops and runs jobs (preferring this worker's own deque, then stealing, then the
+    /// injector) until `latch.probe()` is true
+    pub unsafe fn wait_until<L: Probe>(&self, latch: &L) {
+        while !latch.probe() {
+            if let Some(job) = self.find_work() {
+                job.execute();
+            } else {
+                (*self.registry).sleep.snooze();
+            }
+        }
+    }
+
+    unsafe fn find_work(&self) -> Option<JobRef> {
+        if let Some(job) = self.deque.pop() {
+            return Some(job);
+        }
+
+        (*self.registry).steal_or_inject(self.index)
+    }
+}
+
+/// Shared state for a pool of worker threads.
+///
+/// Dropping the last `Arc<Registry>` (e.g. when a `ThreadPool` goes out of scope) tells every
+/// worker to stop once it next finds itself with no work to do, and blocks until they've all
+/// exited. The process-wide [default registry](fn.default_registry.html) is deliberately leaked
+/// instead, so its threads live for the life of the process.
+pub struct Registry {
+    stealers: Vec<Stealer<JobRef>>,
+    injector: Mutex<VecDeque<JobRef>>,
+    sleep: Sleep,
+    terminate: AtomicBool,
+    threads: Mutex<Vec<thread::JoinHandle<()>>>,
+}
+
+impl Registry {
+    pub fn new(num_threads: usize) -> ::std::sync::Arc<Registry> {
+        let num_threads = if num_threads == 0 { 1 } else { num_threads };
+
+        // boxed so each deque keeps its address when it's moved into its worker thread below;
+        // the stealers hold raw pointers into it
+        let deques: Vec<_> = (0..num_threads).map(|_| Box::new(Deque::new())).collect();
+        let stealers = deques.iter().map(|deque| deque.stealer()).collect();
+
+        let registry = ::std::sync::Arc::new(Registry {
+            stealers: stealers,
+            injector: Mutex::new(VecDeque::new()),
+            sleep: Sleep::new(),
+            terminate: AtomicBool::new(false),
+            threads: Mutex::new(Vec::with_capacity(num_threads)),
+        });
+
+        let mut threads = registry.threads.lock().unwrap();
+
+        for (index, deque) in deques.into_iter().enumerate() {
+            // not a clone of `registry`: a worker holding its own `Arc` would keep the
+            // registry alive forever, and `Registry::drop` would then never run to tell it
+            // to stop (see `RegistryPtr`'s doc comment)
+            let registry_ptr = RegistryPtr(&*registry as *const Registry);
+
+            threads.push(thread::spawn(move || {
+                let registry_ptr = registry_ptr;
+
+                let worker = WorkerThread {
+                    index: index,
+                    deque: deque,
+                    registry: registry_ptr.0,
+                };
+
+                WORKER_THREAD.with(|cell| cell.set(&worker as *const WorkerThread));
+
+                unsafe { (*registry_ptr.0).main_loop(&worker) };
+            }));
+        }
+
+        drop(threads);
+
+        registry
+    }
+
+    fn main_loop(&self, worker: &WorkerThread) {
+        loop {
+            match unsafe { worker.find_work() } {
+                Some(job) => unsafe { job.execute() },
+                None => {
+                    if self.terminate.load(Ordering::SeqCst) {
+                        return;
+                    }
+
+                    self.sleep.snooze();
+                }
+            }
+        }
+    }
+
+    /// Tries to steal from every other worker in turn, then falls back to the injector
+    unsafe fn steal_or_inject(&self, thief: usize) -> Option<JobRef> {
+        for i in 0..self.stealers.len() {
+            let victim = (thief + 1 + i) % self.stealers.len();
+
+            if victim == thief {
+                continue;
+            }
+
+            loop {
+                match self.stealers[victim].steal() {
+                    Steal::Data(job) => return Some(job),
+                    Steal::Empty => break,
+                    Steal::Retry => continue,
+                }
+            }
+        }
+
+        self.injector.lock().unwrap().pop_front()
+    }
+
+    /// The number of worker threads backing this pool
+    pub fn num_threads(&self) -> usize {
+        self.stealers.len()
+    }
+
+    /// Hands `job` to the pool from a thread that isn't one of its own workers
+    pub fn inject(&self, job: JobRef) {
+        self.injector.lock().unwrap().push_back(job);
+        self.sleep.wake_all();
+    }
+
+    /// Blocks the calling thread until `latch` is set, helping drain the pool in the meantime
+    /// if the caller happens to be one of its own workers
+    pub fn wait_until<L: Probe>(&self, latch: &L) {
+        match WorkerThread::current() {
+            Some(worker) => unsafe { (*worker).wait_until(latch) },
+            None => latch.wait(),
+        }
+    }
+}
+
+impl Drop for Registry {
+    /// Tells every worker thread to stop once it's next idle, and waits for them to do so.
+    ///
+    /// Safe even though workers only hold a raw `*const Registry` (see `RegistryPtr`): this
+    /// method runs, and thus `self` stays valid, until every worker has already returned from
+    /// its `main_loop` and been joined.
+    ///
+    /// Don't let the last `Arc<Registry>` drop from inside a job running on that same registry
+    /// (e.g. a `ThreadPool` captured and dropped by a closure passed to its own `divide`): the
+    /// worker running that job would then be joining itself here and hang forever.
+    fn drop(&mut self) {
+        self.terminate.store(true, Ordering::SeqCst);
+        self.sleep.wake_all();
+
+        for thread in self.threads.lock().unwrap().drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+static DEFAULT_REGISTRY: Once = ONCE_INIT;
+static mut DEFAULT_REGISTRY_PTR: *const () = 0 as *const ();
+
+/// The lazily-initialized, process-wide pool that the free functions (`divide`, `apply`, ...)
+/// schedule onto
+pub fn default_registry() -> ::std::sync::Arc<Registry> {
+    unsafe {
+        DEFAULT_REGISTRY.call_once(|| {
+            let registry = Registry::new(os::num_cpus());
+            let boxed = Box::new(registry);
+            DEFAULT_REGISTRY_PTR = Box::into_raw(boxed) as *const ();
+        });
+
+        (*(DEFAULT_REGISTRY_PTR as *const ::std::sync::Arc<Registry>)).clone()
+    }
+}
@@ -0,0 +1,152 @@
+//! A configurable handle to a pool of worker threads
+//!
+//! The free functions in this crate ([`divide`](fn.divide.html), [`apply`](fn.apply.html),
+//! [`reduce`](fn.reduce.html), [`join`](fn.join.html)) schedule onto a single,
+//! lazily-initialized, process-wide pool sized to `std::os::num_cpus()`. Building your own
+//! `ThreadPool` instead lets you cap parallelism, reuse a fixed set of workers across many
+//! calls, or run several independent parallel workloads without oversubscribing cores.
+
+use std::os;
+use std::sync::Arc;
+
+use apply;
+use divide;
+use join;
+use reduce;
+use registry::Registry;
+
+/// A handle to a pool of worker threads
+///
+/// Build one with [`ThreadPool::new`](#method.new), then call `.divide(...)`, `.apply(...)`,
+/// `.reduce(...)` or `.join(...)` on it the same way you'd call the free function of the same
+/// name.
+///
+/// Dropping a `ThreadPool` blocks until its worker threads have shut down, so creating one
+/// per independent workload (rather than per call) is the intended way to run several of them
+/// without oversubscribing cores.
+///
+/// # Example
+///
+/// ```
+/// extern crate parallel;
+///
+/// # fn main() {
+/// let pool = parallel::ThreadPool::new().num_threads(4).build();
+///
+/// let mut v = (1..10).collect::<Vec<usize>>();
+/// pool.apply(v.as_mut_slice(), |x| *x += 1);
+/// # }
+/// ```
+pub struct ThreadPool {
+    registry: Arc<Registry>,
+}
+
+impl ThreadPool {
+    /// Starts building a `ThreadPool`, defaulting to `std::os::num_cpus()` worker threads
+    pub fn new() -> ThreadPoolBuilder {
+        ThreadPoolBuilder { num_threads: None }
+    }
+
+    /// The number of worker threads backing this pool
+    pub fn num_threads(&self) -> usize {
+        self.registry.num_threads()
+    }
+
+    /// See [`divide`](fn.divide.html)
+    pub fn divide<T, F>(&self, data: &mut [T], granularity: usize, operation: F) where
+        T: Send,
+        F: Fn(&mut [T], usize) + Sync,
+    {
+        divide::divide_in(&*self.registry, data, granularity, operation)
+    }
+
+    /// See [`apply`](fn.apply.html)
+    pub fn apply<T, F>(&self, data: &mut [T], operation: F) where
+        T: Send,
+        F: Fn(&mut T) + Sync,
+    {
+        apply::apply_in(&*self.registry, data, operation)
+    }
+
+    /// See [`reduce`](fn.reduce.html)
+    pub fn reduce<T, A, M, C>(
+        &self,
+        data: &[T],
+        granularity: usize,
+        identity: A,
+        map: M,
+        combine: C,
+    ) -> A where
+        T: Sync,
+        A: Send,
+        M: Fn(&[T], usize) -> A + Sync,
+        C: Fn(A, A) -> A,
+    {
+        reduce::reduce_in(&*self.registry, data, granularity, identity, map, combine)
+    }
+
+    /// See [`join`](fn.join.html)
+    pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB) where
+        A: FnOnce() -> RA + Send,
+        RA: Send,
+        B: FnOnce() -> RB + Send,
+    {
+        join::join_in(&*self.registry, a, b)
+    }
+}
+
+/// Builds a [`ThreadPool`](struct.ThreadPool.html)
+pub struct ThreadPoolBuilder {
+    num_threads: Option<usize>,
+}
+
+impl ThreadPoolBuilder {
+    /// Sets the number of worker threads. Defaults to `std::os::num_cpus()`.
+    pub fn num_threads(mut self, num_threads: usize) -> ThreadPoolBuilder {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Spawns the worker threads and returns the pool
+    pub fn build(self) -> ThreadPool {
+        let num_threads = self.num_threads.unwrap_or_else(os::num_cpus);
+
+        ThreadPool { registry: Registry::new(num_threads) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn divide_apply_reduce_join() {
+        let pool = super::ThreadPool::new().num_threads(2).build();
+
+        let mut v = (0..1_000).collect::<Vec<u32>>();
+        pool.divide(v.as_mut_slice(), 100, |chunk, _| {
+            for x in chunk.iter_mut() {
+                *x += 1;
+            }
+        });
+        assert_eq!(v, (1..1_001).collect::<Vec<u32>>());
+
+        pool.apply(v.as_mut_slice(), |x| *x -= 1);
+        assert_eq!(v, (0..1_000).collect::<Vec<u32>>());
+
+        let sum = pool.reduce(&v, 100, 0u64, |chunk, _| {
+            chunk.iter().fold(0u64, |acc, &x| acc + x as u64)
+        }, |a, b| a + b);
+        assert_eq!(sum, (0..1_000u64).fold(0, |acc, x| acc + x));
+
+        let (a, b) = pool.join(|| 1, || 2);
+        assert_eq!((a, b), (1, 2));
+    }
+
+    // regression test for worker threads being leaked for the life of the process: this would
+    // hang (or at least leave `pool`'s threads spinning forever) if `Registry`'s `Drop` didn't
+    // actually tell them to stop
+    #[test]
+    fn drop_joins_worker_threads() {
+        let pool = super::ThreadPool::new().num_threads(4).build();
+        drop(pool);
+    }
+}